@@ -0,0 +1,88 @@
+use anyhow::Result;
+use clap::{Parser, Subcommand};
+
+use pueue_lib::message::CleanRequest;
+use pueue_lib::settings::Settings;
+
+use crate::query::parse_datetime;
+
+/// This is used to parse the input for the CLI.
+#[derive(Parser, Debug)]
+#[command(
+    name = "pueue",
+    about = "Interact with the Pueue daemon",
+    version = env!("CARGO_PKG_VERSION")
+)]
+pub struct CliArguments {
+    #[command(subcommand)]
+    pub cmd: SubCommand,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum SubCommand {
+    /// Display the current status of all tasks in a table.
+    Status {
+        /// Print the current state as json to stdout.
+        /// This does not include the output of tasks.
+        #[arg(short, long)]
+        json: bool,
+
+        /// Only show tasks of a specific group.
+        #[arg(short, long)]
+        group: Option<String>,
+
+        /// Filter, sort and select the columns of the status table with a small query
+        /// language, e.g. `status=running order_by end desc`.
+        query: Vec<String>,
+    },
+
+    /// Like `status`, but without colors and other formatting that's not intended for
+    /// scripts, e.g. syntax highlighting.
+    FormatStatus {
+        /// Only show tasks of a specific group.
+        #[arg(short, long)]
+        group: Option<String>,
+    },
+
+    /// Remove all finished tasks from the list (also clears failed ones).
+    Clean {
+        /// Only clean tasks that finished successfully.
+        #[arg(short, long)]
+        successful_only: bool,
+
+        /// Only clean tasks of a specific group.
+        #[arg(short, long)]
+        group: Option<String>,
+
+        /// Only clean tasks that finished before this point in time, e.g.
+        /// `--before "1 week ago"` or `--before "2026-07-01 00:00:00"`. Accepts the
+        /// same relative/fuzzy expressions as the status query language.
+        #[arg(long)]
+        before: Option<String>,
+    },
+}
+
+impl SubCommand {
+    /// Resolve a `Clean` subcommand into the `CleanRequest` network message, parsing
+    /// `--before` with the same datetime rules as the status query language.
+    pub fn into_clean_request(self, settings: &Settings) -> Result<CleanRequest> {
+        let SubCommand::Clean {
+            successful_only,
+            group,
+            before,
+        } = self
+        else {
+            unreachable!("into_clean_request called on a non-Clean subcommand");
+        };
+
+        let before = before
+            .map(|raw| parse_datetime(&raw, settings))
+            .transpose()?;
+
+        Ok(CleanRequest {
+            successful_only,
+            group,
+            before,
+        })
+    }
+}
@@ -1,10 +1,9 @@
 use std::string::ToString;
 
-use anyhow::{Context, Result};
+use anyhow::Result;
 use chrono::{Duration, Local};
 use comfy_table::presets::UTF8_HORIZONTAL_ONLY;
 use comfy_table::*;
-use pest::Parser;
 
 use pueue_lib::settings::Settings;
 use pueue_lib::state::{State, PUEUE_DEFAULT_GROUP};
@@ -12,14 +11,14 @@ use pueue_lib::task::{Task, TaskResult, TaskStatus};
 
 use super::{helper::*, OutputStyle};
 use crate::cli::SubCommand;
-use crate::query::*;
+use crate::query::{apply_query, status_label, Column, Direction};
 
 /// Print the current state of the daemon in a nicely formatted table.
 /// We pass the tasks as a separate parameter and as a list.
 /// This allows us to print the tasks in any user-defined order.
 pub fn print_state(
     state: State,
-    tasks: Vec<Task>,
+    mut tasks: Vec<Task>,
     cli_command: &SubCommand,
     style: &OutputStyle,
     settings: &Settings,
@@ -30,12 +29,13 @@ pub fn print_state(
         _ => panic!("Got wrong Subcommand {cli_command:?} in print_state. This shouldn't happen!"),
     };
 
-    if let Some(query) = query {
-        let full_query = query.join(" ");
-        let parsed =
-            QueryParser::parse(Rule::query, &full_query).context("Failed to parse query")?;
-        dbg!(parsed);
-    }
+    let (order_by, selected_columns) = if let Some(query) = query {
+        let query_result = apply_query(query, settings)?;
+        tasks.retain(|task| query_result.filters.iter().all(|filter| filter(task)));
+        (query_result.order_by, query_result.selected_columns)
+    } else {
+        (None, None)
+    };
 
     // If the json flag is specified, print the state as json and exit.
     if json {
@@ -44,11 +44,19 @@ pub fn print_state(
     }
 
     if let Some(group) = group_only {
-        print_single_group(state, tasks, settings, style, group);
+        print_single_group(
+            state,
+            tasks,
+            settings,
+            style,
+            group,
+            order_by,
+            selected_columns,
+        );
         return Ok(());
     }
 
-    print_all_groups(state, tasks, settings, style);
+    print_all_groups(state, tasks, settings, style, order_by, selected_columns);
 
     Ok(())
 }
@@ -59,6 +67,8 @@ fn print_single_group(
     settings: &Settings,
     style: &OutputStyle,
     group_name: String,
+    order_by: Option<(Column, Direction)>,
+    selected_columns: Option<Vec<Column>>,
 ) {
     // Sort all tasks by their respective group;
     let mut sorted_tasks = sort_tasks_by_group(tasks);
@@ -80,10 +90,17 @@ fn print_single_group(
         println!("Task list is empty. Add tasks with `pueue add -g {group_name} -- [cmd]`");
         return;
     }
-    print_table(tasks, style, settings);
+    print_table(tasks, style, settings, order_by, selected_columns.as_deref());
 }
 
-fn print_all_groups(state: State, tasks: Vec<Task>, settings: &Settings, style: &OutputStyle) {
+fn print_all_groups(
+    state: State,
+    tasks: Vec<Task>,
+    settings: &Settings,
+    style: &OutputStyle,
+    order_by: Option<(Column, Direction)>,
+    selected_columns: Option<Vec<Column>>,
+) {
     // Early exit and hint if there are no tasks in the queue
     // Print the state of the default group anyway, since this is information one wants to
     // see most of the time anyway.
@@ -99,18 +116,18 @@ fn print_all_groups(state: State, tasks: Vec<Task>, settings: &Settings, style:
     }
 
     // Sort all tasks by their respective group;
-    let sorted_tasks = sort_tasks_by_group(tasks);
+    let mut sorted_tasks = sort_tasks_by_group(tasks);
 
     // Always print the default queue at the very top, if no specific group is requested.
     if sorted_tasks.get(PUEUE_DEFAULT_GROUP).is_some() {
-        let tasks = sorted_tasks.get(PUEUE_DEFAULT_GROUP).unwrap();
+        let tasks = sorted_tasks.get_mut(PUEUE_DEFAULT_GROUP).unwrap();
         let headline = get_group_headline(
             PUEUE_DEFAULT_GROUP,
             state.groups.get(PUEUE_DEFAULT_GROUP).unwrap(),
             style,
         );
         println!("{headline}");
-        print_table(tasks, style, settings);
+        print_table(tasks, style, settings, order_by, selected_columns.as_deref());
 
         // Add a newline if there are further groups to be printed
         if sorted_tasks.len() > 1 {
@@ -119,7 +136,7 @@ fn print_all_groups(state: State, tasks: Vec<Task>, settings: &Settings, style:
     }
 
     // Print a table for every other group that has any tasks
-    let mut sorted_iter = sorted_tasks.iter().peekable();
+    let mut sorted_iter = sorted_tasks.iter_mut().peekable();
     while let Some((group, tasks)) = sorted_iter.next() {
         // We always want to print the default group at the very top.
         // That's why we print it before this loop and skip it in here.
@@ -129,7 +146,7 @@ fn print_all_groups(state: State, tasks: Vec<Task>, settings: &Settings, style:
 
         let headline = get_group_headline(group, state.groups.get(group).unwrap(), style);
         println!("{headline}");
-        print_table(tasks, style, settings);
+        print_table(tasks, style, settings, order_by, selected_columns.as_deref());
 
         // Add a newline between groups
         if sorted_iter.peek().is_some() {
@@ -138,67 +155,62 @@ fn print_all_groups(state: State, tasks: Vec<Task>, settings: &Settings, style:
     }
 }
 
-/// Print some tasks into a nicely formatted table
-fn print_table(tasks: &[Task], style: &OutputStyle, settings: &Settings) {
+/// The columns shown by default, with the enqueue-at/dependency/label columns only
+/// added when at least one task in the table actually has something to show there.
+fn default_columns(tasks: &[Task]) -> Vec<Column> {
     let (has_delayed_tasks, has_dependencies, has_labels) = has_special_columns(tasks);
 
-    // Create table header row
-    let mut headers = vec![Cell::new("Id"), Cell::new("Status")];
-
+    let mut columns = vec![Column::Id, Column::Status];
     if has_delayed_tasks {
-        headers.push(Cell::new("Enqueue At"));
+        columns.push(Column::EnqueueAt);
     }
     if has_dependencies {
-        headers.push(Cell::new("Deps"));
+        columns.push(Column::Dependencies);
     }
     if has_labels {
-        headers.push(Cell::new("Label"));
+        columns.push(Column::Label);
     }
+    columns.extend([Column::Command, Column::Path, Column::Start, Column::End]);
 
-    headers.append(&mut vec![
-        Cell::new("Command"),
-        Cell::new("Path"),
-        Cell::new("Start"),
-        Cell::new("End"),
-    ]);
-
-    // Initialize comfy table.
-    let mut table = Table::new();
-    table
-        .set_content_arrangement(ContentArrangement::Dynamic)
-        .load_preset(UTF8_HORIZONTAL_ONLY)
-        .set_header(headers);
+    columns
+}
 
-    // Explicitly force styling, in case we aren't on a tty, but `--color=always` is set.
-    if style.enabled {
-        table.enforce_styling();
+fn column_header(column: Column) -> &'static str {
+    match column {
+        Column::Id => "Id",
+        Column::Status => "Status",
+        Column::EnqueueAt => "Enqueue At",
+        Column::Dependencies => "Deps",
+        Column::Label => "Label",
+        Column::Command => "Command",
+        Column::Path => "Path",
+        Column::Start => "Start",
+        Column::End => "End",
     }
+}
 
-    // Add rows one by one.
-    for task in tasks.iter() {
-        let mut row = Row::new();
-        if let Some(height) = settings.client.max_status_lines {
-            row.max_height(height);
+/// Build the styled cell for a single `(task, column)` pair.
+fn column_cell(column: Column, task: &Task, settings: &Settings, style: &OutputStyle) -> Cell {
+    match column {
+        Column::Id => Cell::new(&task.id),
+        Column::Status => {
+            // Determine the human readable task status representation and the respective color.
+            let status_string = task.status.to_string();
+            let (status_text, color) = match &task.status {
+                TaskStatus::Running => (status_string, Color::Green),
+                TaskStatus::Paused | TaskStatus::Locked => (status_string, Color::White),
+                TaskStatus::Done(result) => match result {
+                    TaskResult::Success => (TaskResult::Success.to_string(), Color::Green),
+                    TaskResult::DependencyFailed => ("Dependency failed".to_string(), Color::Red),
+                    TaskResult::FailedToSpawn(_) => ("Failed to spawn".to_string(), Color::Red),
+                    TaskResult::Failed(code) => (format!("Failed ({code})"), Color::Red),
+                    _ => (result.to_string(), Color::Red),
+                },
+                _ => (status_string, Color::Yellow),
+            };
+            style.styled_cell(status_text, Some(color), None)
         }
-        row.add_cell(Cell::new(&task.id));
-
-        // Determine the human readable task status representation and the respective color.
-        let status_string = task.status.to_string();
-        let (status_text, color) = match &task.status {
-            TaskStatus::Running => (status_string, Color::Green),
-            TaskStatus::Paused | TaskStatus::Locked => (status_string, Color::White),
-            TaskStatus::Done(result) => match result {
-                TaskResult::Success => (TaskResult::Success.to_string(), Color::Green),
-                TaskResult::DependencyFailed => ("Dependency failed".to_string(), Color::Red),
-                TaskResult::FailedToSpawn(_) => ("Failed to spawn".to_string(), Color::Red),
-                TaskResult::Failed(code) => (format!("Failed ({code})"), Color::Red),
-                _ => (result.to_string(), Color::Red),
-            },
-            _ => (status_string, Color::Yellow),
-        };
-        row.add_cell(style.styled_cell(status_text, Some(color), None));
-
-        if has_delayed_tasks {
+        Column::EnqueueAt => {
             if let TaskStatus::Stashed {
                 enqueue_at: Some(enqueue_at),
             } = task.status
@@ -211,38 +223,82 @@ fn print_table(tasks: &[Task], style: &OutputStyle, settings: &Settings) {
                 } else {
                     enqueue_at.format(&settings.client.status_datetime_format)
                 };
-                row.add_cell(Cell::new(formatted_enqueue_at));
+                Cell::new(formatted_enqueue_at)
             } else {
-                row.add_cell(Cell::new(""));
+                Cell::new("")
             }
         }
-
-        if has_dependencies {
+        Column::Dependencies => {
             let text = task
                 .dependencies
                 .iter()
                 .map(|id| id.to_string())
                 .collect::<Vec<String>>()
                 .join(", ");
-            row.add_cell(Cell::new(text));
+            Cell::new(text)
         }
-
-        if has_labels {
-            row.add_cell(Cell::new(&task.label.as_deref().unwrap_or_default()));
+        Column::Label => Cell::new(task.label.as_deref().unwrap_or_default()),
+        Column::Command => {
+            if settings.client.show_expanded_aliases {
+                Cell::new(&task.command)
+            } else {
+                Cell::new(&task.original_command)
+            }
         }
+        Column::Path => Cell::new(task.path.to_string_lossy()),
+        Column::Start => Cell::new(formatted_start(task, settings)),
+        Column::End => Cell::new(formatted_end(task, settings)),
+    }
+}
 
-        // Add command and path.
-        if settings.client.show_expanded_aliases {
-            row.add_cell(Cell::new(&task.command));
-        } else {
-            row.add_cell(Cell::new(&task.original_command));
+/// Print some tasks into a nicely formatted table.
+///
+/// `selected_columns` drives both the header and the per-row cells, preserving the
+/// user's requested order. When `None`, we fall back to the default column set.
+fn print_table(
+    tasks: &mut [Task],
+    style: &OutputStyle,
+    settings: &Settings,
+    order_by: Option<(Column, Direction)>,
+    selected_columns: Option<&[Column]>,
+) {
+    if let Some((column, direction)) = order_by {
+        sort_by_column(tasks, column, direction);
+    }
+
+    let columns: Vec<Column> = match selected_columns {
+        Some(columns) => columns.to_vec(),
+        None => default_columns(tasks),
+    };
+
+    // Create table header row
+    let headers: Vec<Cell> = columns
+        .iter()
+        .map(|column| Cell::new(column_header(*column)))
+        .collect();
+
+    // Initialize comfy table.
+    let mut table = Table::new();
+    table
+        .set_content_arrangement(ContentArrangement::Dynamic)
+        .load_preset(UTF8_HORIZONTAL_ONLY)
+        .set_header(headers);
+
+    // Explicitly force styling, in case we aren't on a tty, but `--color=always` is set.
+    if style.enabled {
+        table.enforce_styling();
+    }
+
+    // Add rows one by one.
+    for task in tasks.iter() {
+        let mut row = Row::new();
+        if let Some(height) = settings.client.max_status_lines {
+            row.max_height(height);
         }
-        row.add_cell(Cell::new(&task.path.to_string_lossy()));
 
-        // Add start and end info
-        let (start, end) = formatted_start_end(task, settings);
-        row.add_cell(Cell::new(start));
-        row.add_cell(Cell::new(end));
+        for column in &columns {
+            row.add_cell(column_cell(*column, task, settings, style));
+        }
 
         table.add_row(row);
     }
@@ -251,25 +307,46 @@ fn print_table(tasks: &[Task], style: &OutputStyle, settings: &Settings) {
     println!("{table}");
 }
 
-/// Returns the formatted `start` and `end` text for a given task.
-///
-/// 1. If the start || end is today, skip the date.
-/// 2. Otherwise show the date in both.
-///
-/// If the task doesn't have a start and/or end yet, an empty string will be returned
-/// for the respective field.
-fn formatted_start_end(task: &Task, settings: &Settings) -> (String, String) {
-    // Get the start time.
-    // If the task didn't start yet, just return two empty strings.
-    let start = match task.start {
-        Some(start) => start,
-        None => return ("".into(), "".into()),
+/// Sort tasks in-place by the column and direction requested via `order_by`.
+/// Datetime columns sort tasks without a recorded value last, regardless of direction.
+fn sort_by_column(tasks: &mut [Task], column: Column, direction: Direction) {
+    use std::cmp::Ordering;
+
+    use crate::query::datetime_of;
+
+    let directed = |ordering: Ordering| match direction {
+        Direction::Ascending => ordering,
+        Direction::Descending => ordering.reverse(),
+    };
+
+    tasks.sort_by(|a, b| match column {
+        Column::Id => directed(a.id.cmp(&b.id)),
+        Column::Command => directed(a.original_command.cmp(&b.original_command)),
+        Column::Label => directed(a.label.cmp(&b.label)),
+        Column::Path => directed(a.path.cmp(&b.path)),
+        Column::Status => directed(status_label(a).cmp(&status_label(b))),
+        Column::Start | Column::End | Column::EnqueueAt => {
+            match (datetime_of(a, column), datetime_of(b, column)) {
+                (Some(a), Some(b)) => directed(a.cmp(&b)),
+                // Tasks without a recorded value always sort last, independent of direction.
+                (Some(_), None) => Ordering::Less,
+                (None, Some(_)) => Ordering::Greater,
+                (None, None) => Ordering::Equal,
+            }
+        }
+        Column::Dependencies => Ordering::Equal,
+    });
+}
+
+/// Returns the formatted `start` text for a given task, or an empty string if it hasn't
+/// started yet. If the start is today, the date is skipped and only the time is shown.
+fn formatted_start(task: &Task, settings: &Settings) -> String {
+    let Some(start) = task.start else {
+        return "".into();
     };
 
-    // If the task started today, just show the time.
-    // Otherwise show the full date and time.
     let started_today = start >= Local::today().and_hms(0, 0, 0);
-    let formatted_start = if started_today {
+    if started_today {
         start
             .format(&settings.client.status_time_format)
             .to_string()
@@ -277,23 +354,120 @@ fn formatted_start_end(task: &Task, settings: &Settings) -> (String, String) {
         start
             .format(&settings.client.status_datetime_format)
             .to_string()
-    };
+    }
+}
 
-    // Get finish time, if already set. Otherwise only return the formatted start.
-    let end = match task.end {
-        Some(end) => end,
-        None => return (formatted_start, "".into()),
+/// Returns the formatted `end` text for a given task, or an empty string if it hasn't
+/// finished yet. If the end is today, the date is skipped and only the time is shown.
+fn formatted_end(task: &Task, settings: &Settings) -> String {
+    let Some(end) = task.end else {
+        return "".into();
     };
 
-    // If the task ended today we only show the time.
-    // In all other circumstances, we show the full date.
     let finished_today = end >= Local::today().and_hms(0, 0, 0);
-    let formatted_end = if finished_today {
+    if finished_today {
         end.format(&settings.client.status_time_format).to_string()
     } else {
         end.format(&settings.client.status_datetime_format)
             .to_string()
-    };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::path::PathBuf;
+
+    use pueue_lib::state::PUEUE_DEFAULT_GROUP;
+
+    use super::*;
+
+    fn stub_task(id: usize, end: Option<chrono::DateTime<Local>>) -> Task {
+        let mut task = Task::new(
+            "true".to_string(),
+            PathBuf::from("/tmp"),
+            HashMap::new(),
+            PUEUE_DEFAULT_GROUP.to_string(),
+            0,
+            Vec::new(),
+            None,
+        );
+        task.id = id;
+        task.end = end;
+        task
+    }
 
-    (formatted_start, formatted_end)
+    #[test]
+    fn descending_order_still_sorts_missing_values_last() {
+        let now = Local::now();
+        let mut tasks = vec![
+            stub_task(0, None),
+            stub_task(1, Some(now)),
+            stub_task(2, Some(now - Duration::days(1))),
+        ];
+
+        sort_by_column(&mut tasks, Column::End, Direction::Descending);
+
+        let ids: Vec<usize> = tasks.iter().map(|task| task.id).collect();
+        assert_eq!(ids, vec![1, 2, 0]);
+    }
+
+    #[test]
+    fn ascending_order_sorts_missing_values_last() {
+        let now = Local::now();
+        let mut tasks = vec![
+            stub_task(0, None),
+            stub_task(1, Some(now)),
+            stub_task(2, Some(now - Duration::days(1))),
+        ];
+
+        sort_by_column(&mut tasks, Column::End, Direction::Ascending);
+
+        let ids: Vec<usize> = tasks.iter().map(|task| task.id).collect();
+        assert_eq!(ids, vec![2, 1, 0]);
+    }
+
+    #[test]
+    fn default_columns_omits_special_columns_when_unused() {
+        let tasks = vec![stub_task(0, None)];
+
+        let columns = default_columns(&tasks);
+        assert_eq!(
+            columns,
+            vec![
+                Column::Id,
+                Column::Status,
+                Column::Command,
+                Column::Path,
+                Column::Start,
+                Column::End,
+            ]
+        );
+    }
+
+    #[test]
+    fn default_columns_includes_label_when_a_task_has_one() {
+        let mut task = stub_task(0, None);
+        task.label = Some("ci".to_string());
+
+        assert!(default_columns(&[task]).contains(&Column::Label));
+    }
+
+    #[test]
+    fn default_columns_includes_dependencies_when_a_task_has_any() {
+        let mut task = stub_task(0, None);
+        task.dependencies = vec![1, 2];
+
+        assert!(default_columns(&[task]).contains(&Column::Dependencies));
+    }
+
+    #[test]
+    fn default_columns_includes_enqueue_at_when_a_task_is_stashed_with_a_time() {
+        let mut task = stub_task(0, None);
+        task.status = TaskStatus::Stashed {
+            enqueue_at: Some(Local::now()),
+        };
+
+        assert!(default_columns(&[task]).contains(&Column::EnqueueAt));
+    }
 }
@@ -1,5 +1,12 @@
+use anyhow::{bail, Context, Result};
+use chrono::{DateTime, Duration, Local, NaiveDateTime, NaiveTime, TimeZone};
+use pest::iterators::Pair;
+use pest::Parser;
 use pest_derive::Parser;
 
+use pueue_lib::settings::Settings;
+use pueue_lib::task::{Task, TaskStatus};
+
 #[derive(Parser)]
 #[grammar_inline = r#"
 WHITESPACE = _{ " " }
@@ -21,6 +28,536 @@ multiple_columns = { column ~ (COMMA ~ column )* }
 select = { ^"select" }
 select_query = { select ~ multiple_columns }
 
-query = { SOI ~ select_query? ~ EOI }
+equal = { "=" }
+not_equal = { "!=" }
+less_than = { "<" }
+greater_than = { ">" }
+contains = { "%=" }
+operator = { not_equal | contains | equal | less_than | greater_than }
+
+quoted_value = @{ "\"" ~ (!"\"" ~ ANY)* ~ "\"" }
+plain_value = @{ !(^"and" ~ (WHITESPACE | EOI)) ~ (!WHITESPACE ~ ANY)+ }
+value = { quoted_value | plain_value }
+
+filter_column = { status | label | command | path | start | end | enqueue_at }
+and = _{ ^"and" }
+filter = { filter_column ~ operator ~ value }
+filter_query = { filter ~ (and ~ filter)* }
+
+order_by = { ^"order_by" }
+ascending = { ^"asc" }
+descending = { ^"desc" }
+direction = { ascending | descending }
+order_by_query = { order_by ~ column ~ direction? }
+
+clause = { filter_query | order_by_query | select_query }
+query = { SOI ~ clause* ~ EOI }
 "#]
 pub struct QueryParser;
+
+/// The columns that can be selected, filtered or ordered by in a status query.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Column {
+    Id,
+    Status,
+    Command,
+    Label,
+    Path,
+    EnqueueAt,
+    Dependencies,
+    Start,
+    End,
+}
+
+impl Column {
+    fn from_pair(pair: Pair<Rule>) -> Column {
+        match pair.as_rule() {
+            Rule::id => Column::Id,
+            Rule::status => Column::Status,
+            Rule::command => Column::Command,
+            Rule::label => Column::Label,
+            Rule::path => Column::Path,
+            Rule::enqueue_at => Column::EnqueueAt,
+            Rule::dependencies => Column::Dependencies,
+            Rule::start => Column::Start,
+            Rule::end => Column::End,
+            _ => unreachable!("Expected a column rule, got {pair:?}"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum FilterOperator {
+    Equal,
+    NotEqual,
+    LessThan,
+    GreaterThan,
+    Contains,
+}
+
+impl FilterOperator {
+    fn from_pair(pair: Pair<Rule>) -> FilterOperator {
+        match pair.into_inner().next().unwrap().as_rule() {
+            Rule::equal => FilterOperator::Equal,
+            Rule::not_equal => FilterOperator::NotEqual,
+            Rule::less_than => FilterOperator::LessThan,
+            Rule::greater_than => FilterOperator::GreaterThan,
+            Rule::contains => FilterOperator::Contains,
+            _ => unreachable!("Expected an operator rule"),
+        }
+    }
+
+    fn apply<T: PartialEq + PartialOrd>(self, actual: &T, expected: &T) -> bool {
+        match self {
+            FilterOperator::Equal => actual == expected,
+            FilterOperator::NotEqual => actual != expected,
+            FilterOperator::LessThan => actual < expected,
+            FilterOperator::GreaterThan => actual > expected,
+            FilterOperator::Contains => {
+                unreachable!("Contains is only valid for strings and handled separately")
+            }
+        }
+    }
+}
+
+/// The direction tasks should be sorted in for an `order_by` clause.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    Ascending,
+    Descending,
+}
+
+/// A single compiled `<column> <op> <value>` predicate.
+/// Kept as a list in `QueryResult`; a task passes the query only if every filter holds.
+type Filter = Box<dyn Fn(&Task) -> bool>;
+
+/// Everything a parsed status query can influence.
+/// `print_state` applies this to the task list before it's handed off to the table printer.
+#[derive(Default)]
+pub struct QueryResult {
+    pub filters: Vec<Filter>,
+    pub order_by: Option<(Column, Direction)>,
+    pub selected_columns: Option<Vec<Column>>,
+}
+
+/// Parse and compile a `pueue status <query>` query into something `print_state` can apply
+/// to the list of tasks.
+pub fn apply_query(query: &[String], settings: &Settings) -> Result<QueryResult> {
+    let full_query = query.join(" ");
+    let mut parsed =
+        QueryParser::parse(Rule::query, &full_query).context("Failed to parse query")?;
+
+    let mut result = QueryResult::default();
+
+    // `query` has a single top-level pair containing zero or more clauses.
+    for clause in parsed.next().unwrap().into_inner() {
+        if clause.as_rule() == Rule::EOI {
+            continue;
+        }
+
+        let pair = clause.into_inner().next().unwrap();
+        match pair.as_rule() {
+            Rule::filter_query => result.filters.extend(compile_filters(pair, settings)?),
+            Rule::order_by_query => result.order_by = Some(compile_order_by(pair)),
+            Rule::select_query => result.selected_columns = Some(compile_select(pair)),
+            _ => unreachable!("Unexpected clause rule: {pair:?}"),
+        }
+    }
+
+    Ok(result)
+}
+
+fn compile_select(select_query: Pair<Rule>) -> Vec<Column> {
+    let mut inner = select_query.into_inner();
+    inner.next(); // the `select` keyword itself
+    let multiple_columns = inner.next().unwrap();
+
+    multiple_columns
+        .into_inner()
+        .map(|column| Column::from_pair(column.into_inner().next().unwrap()))
+        .collect()
+}
+
+fn compile_order_by(order_by_query: Pair<Rule>) -> (Column, Direction) {
+    let mut inner = order_by_query.into_inner();
+    inner.next(); // the `order_by` keyword itself
+    let column = Column::from_pair(inner.next().unwrap().into_inner().next().unwrap());
+    let direction = inner
+        .next()
+        .map(|direction| match direction.into_inner().next().unwrap().as_rule() {
+            Rule::ascending => Direction::Ascending,
+            Rule::descending => Direction::Descending,
+            _ => unreachable!("Expected a direction rule"),
+        })
+        .unwrap_or(Direction::Ascending);
+
+    (column, direction)
+}
+
+fn compile_filters(filter_query: Pair<Rule>, settings: &Settings) -> Result<Vec<Filter>> {
+    filter_query
+        .into_inner()
+        .map(|filter| compile_filter(filter, settings))
+        .collect()
+}
+
+fn compile_filter(filter: Pair<Rule>, settings: &Settings) -> Result<Filter> {
+    let mut inner = filter.into_inner();
+    let column = Column::from_pair(inner.next().unwrap().into_inner().next().unwrap());
+    let operator = FilterOperator::from_pair(inner.next().unwrap());
+    let raw_value = parse_value(inner.next().unwrap());
+
+    let filter: Filter = match column {
+        Column::Status => {
+            let expected = raw_value.to_lowercase();
+            Box::new(move |task: &Task| compare_strings(&status_label(task), &expected, operator))
+        }
+        Column::Label => {
+            let expected = raw_value;
+            Box::new(move |task: &Task| {
+                compare_strings(task.label.as_deref().unwrap_or_default(), &expected, operator)
+            })
+        }
+        Column::Command => {
+            let expected = raw_value;
+            Box::new(move |task: &Task| {
+                compare_strings(&task.original_command, &expected, operator)
+            })
+        }
+        Column::Path => {
+            let expected = raw_value;
+            Box::new(move |task: &Task| {
+                compare_strings(&task.path.to_string_lossy(), &expected, operator)
+            })
+        }
+        Column::Start | Column::End | Column::EnqueueAt => {
+            if operator == FilterOperator::Contains {
+                bail!("The `%=` operator cannot be used on datetime columns");
+            }
+            let expected = parse_datetime(&raw_value, settings)
+                .with_context(|| format!("Failed to parse datetime value '{raw_value}'"))?;
+            Box::new(move |task: &Task| {
+                let Some(actual) = datetime_of(task, column) else {
+                    return false;
+                };
+                operator.apply(&actual, &expected)
+            })
+        }
+        Column::Id | Column::Dependencies => {
+            bail!("Column is not filterable")
+        }
+    };
+
+    Ok(filter)
+}
+
+/// Extract the relevant `DateTime` of a task for one of the three datetime columns.
+pub(crate) fn datetime_of(task: &Task, column: Column) -> Option<DateTime<Local>> {
+    match column {
+        Column::Start => task.start,
+        Column::End => task.end,
+        Column::EnqueueAt => match task.status {
+            TaskStatus::Stashed { enqueue_at } => enqueue_at,
+            _ => None,
+        },
+        _ => unreachable!("datetime_of called with a non-datetime column"),
+    }
+}
+
+fn compare_strings(actual: &str, expected: &str, operator: FilterOperator) -> bool {
+    match operator {
+        FilterOperator::Contains => actual.contains(expected),
+        _ => operator.apply(&actual.to_string(), &expected.to_string()),
+    }
+}
+
+/// Strip the surrounding quotes off a `quoted_value`, leave `plain_value` untouched.
+fn parse_value(value: Pair<Rule>) -> String {
+    let inner = value.into_inner().next().unwrap();
+    match inner.as_rule() {
+        Rule::quoted_value => {
+            let raw = inner.as_str();
+            raw[1..raw.len() - 1].to_string()
+        }
+        Rule::plain_value => inner.as_str().to_string(),
+        _ => unreachable!("Expected a value rule"),
+    }
+}
+
+/// The human readable, lowercase status label used by the query language.
+/// This intentionally mirrors the strings a user sees in `pueue status`, e.g.
+/// `running`, `success`, `failed`, rather than the internal enum variant names.
+pub(crate) fn status_label(task: &Task) -> String {
+    use pueue_lib::task::TaskResult;
+
+    match &task.status {
+        TaskStatus::Running => "running".to_string(),
+        TaskStatus::Paused => "paused".to_string(),
+        TaskStatus::Locked => "locked".to_string(),
+        TaskStatus::Queued => "queued".to_string(),
+        TaskStatus::Stashed { .. } => "stashed".to_string(),
+        TaskStatus::Done(result) => match result {
+            TaskResult::Success => "success".to_string(),
+            TaskResult::Failed(_) => "failed".to_string(),
+            TaskResult::FailedToSpawn(_) => "failed_to_spawn".to_string(),
+            TaskResult::Killed => "killed".to_string(),
+            TaskResult::Errored => "errored".to_string(),
+            TaskResult::DependencyFailed => "dependency_failed".to_string(),
+        },
+    }
+}
+
+/// Parse a datetime value from user input.
+///
+/// This first tries strict parsing against `settings.client.status_datetime_format`
+/// (the format used when displaying dates), then falls back to a small set of
+/// natural language / relative expressions: `now`, `today`, `yesterday`, `tomorrow`
+/// (optionally followed by a clock time, e.g. `tomorrow 9am`), and `N <unit> ago` /
+/// `in N <unit>` for seconds, minutes, hours, days and weeks.
+///
+/// Shared by the query language's datetime filters and the `clean --before` CLI
+/// option, so both accept the same ergonomic date syntax.
+pub fn parse_datetime(raw: &str, settings: &Settings) -> Result<DateTime<Local>> {
+    if let Ok(naive) = NaiveDateTime::parse_from_str(raw, &settings.client.status_datetime_format)
+    {
+        return Local
+            .from_local_datetime(&naive)
+            .single()
+            .with_context(|| format!("Ambiguous local datetime '{raw}'"));
+    }
+
+    parse_relative_datetime(raw).with_context(|| format!("Could not parse '{raw}' as a datetime"))
+}
+
+/// Resolve a relative/fuzzy datetime expression against `Local::now()`.
+fn parse_relative_datetime(raw: &str) -> Result<DateTime<Local>> {
+    let normalized = raw.trim().to_lowercase();
+    let now = Local::now();
+
+    match normalized.as_str() {
+        "now" | "today" => return Ok(now),
+        "yesterday" => return Ok(now - Duration::days(1)),
+        "tomorrow" => return Ok(now + Duration::days(1)),
+        _ => {}
+    }
+
+    // `today`/`yesterday`/`tomorrow` can be followed by a clock time, e.g. `tomorrow 9am`.
+    for (keyword, day_offset) in [("today", 0), ("yesterday", -1), ("tomorrow", 1)] {
+        let Some(time_spec) = normalized
+            .strip_prefix(keyword)
+            .and_then(|rest| rest.strip_prefix(' '))
+        else {
+            continue;
+        };
+
+        let date = (now + Duration::days(day_offset)).date_naive();
+        let naive = date.and_time(parse_time_of_day(time_spec)?);
+        return Local
+            .from_local_datetime(&naive)
+            .single()
+            .with_context(|| format!("Ambiguous local datetime '{raw}'"));
+    }
+
+    if let Some(rest) = normalized.strip_suffix(" ago") {
+        return Ok(now - parse_duration(rest)?);
+    }
+
+    if let Some(rest) = normalized.strip_prefix("in ") {
+        return Ok(now + parse_duration(rest)?);
+    }
+
+    bail!("Unrecognized datetime expression '{raw}'");
+}
+
+/// Parse a clock time such as `9am`, `9:30am` or `21:00`.
+fn parse_time_of_day(raw: &str) -> Result<NaiveTime> {
+    let upper = raw.trim().to_uppercase();
+
+    for format in ["%H:%M", "%H:%M:%S", "%I:%M%p"] {
+        if let Ok(time) = NaiveTime::parse_from_str(&upper, format) {
+            return Ok(time);
+        }
+    }
+
+    // chrono's "%I%p" refuses to parse a bare hour like "9AM" since it requires a
+    // minute field, so normalize bare-hour input to "9:00AM" and retry the
+    // colon-separated format instead of trying to match it directly.
+    for meridiem in ["AM", "PM"] {
+        if let Some(hour) = upper.strip_suffix(meridiem) {
+            let with_minutes = format!("{}:00{meridiem}", hour.trim());
+            if let Ok(time) = NaiveTime::parse_from_str(&with_minutes, "%I:%M%p") {
+                return Ok(time);
+            }
+        }
+    }
+
+    bail!("Unrecognized time of day '{raw}'")
+}
+
+/// Parse a `N (second|minute|hour|day|week)` expression, singular or plural.
+fn parse_duration(expression: &str) -> Result<Duration> {
+    let mut parts = expression.split_whitespace();
+    let amount: i64 = parts
+        .next()
+        .context("Expected a number of units, e.g. '2 hours'")?
+        .parse()
+        .context("Expected a number of units, e.g. '2 hours'")?;
+    let unit = parts
+        .next()
+        .context("Expected a time unit, e.g. '2 hours'")?
+        .trim_end_matches('s');
+
+    let duration = match unit {
+        "second" => Duration::seconds(amount),
+        "minute" => Duration::minutes(amount),
+        "hour" => Duration::hours(amount),
+        "day" => Duration::days(amount),
+        "week" => Duration::weeks(amount),
+        _ => bail!("Unknown time unit '{unit}'"),
+    };
+
+    Ok(duration)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::path::PathBuf;
+
+    use pueue_lib::state::PUEUE_DEFAULT_GROUP;
+
+    use super::*;
+
+    fn stub_task() -> Task {
+        Task::new(
+            "true".to_string(),
+            PathBuf::from("/tmp"),
+            HashMap::new(),
+            PUEUE_DEFAULT_GROUP.to_string(),
+            0,
+            Vec::new(),
+            None,
+        )
+    }
+
+    #[test]
+    fn unquoted_value_containing_and_as_a_substring_is_not_split() {
+        let settings = Settings::default();
+        let mut task = stub_task();
+        task.original_command = "sandbox".to_string();
+
+        let result = apply_query(&["command=sandbox".to_string()], &settings).unwrap();
+        assert_eq!(result.filters.len(), 1);
+        assert!(result.filters[0](&task));
+    }
+
+    #[test]
+    fn unquoted_value_ending_in_and_is_not_split() {
+        let settings = Settings::default();
+        let mut task = stub_task();
+        task.label = Some("band".to_string());
+
+        let result = apply_query(&["label=band".to_string()], &settings).unwrap();
+        assert_eq!(result.filters.len(), 1);
+        assert!(result.filters[0](&task));
+    }
+
+    #[test]
+    fn bare_and_keyword_still_joins_two_filters() {
+        let settings = Settings::default();
+        let mut task = stub_task();
+        task.status = TaskStatus::Running;
+        task.label = Some("ci".to_string());
+
+        let result =
+            apply_query(&["status=running and label=ci".to_string()], &settings).unwrap();
+        assert_eq!(result.filters.len(), 2);
+        assert!(result.filters.iter().all(|filter| filter(&task)));
+    }
+
+    #[test]
+    fn select_query_compiles_the_requested_columns_in_order() {
+        let settings = Settings::default();
+
+        let result = apply_query(&["select id,status,label".to_string()], &settings).unwrap();
+        assert_eq!(
+            result.selected_columns,
+            Some(vec![Column::Id, Column::Status, Column::Label])
+        );
+    }
+
+    #[test]
+    fn select_query_is_none_when_no_select_clause_is_given() {
+        let settings = Settings::default();
+
+        let result = apply_query(&["status=running".to_string()], &settings).unwrap();
+        assert_eq!(result.selected_columns, None);
+    }
+
+    #[test]
+    fn relative_datetime_accepts_a_clock_time_after_the_day() {
+        use chrono::Timelike;
+
+        let parsed = parse_relative_datetime("tomorrow 9am").unwrap();
+        let expected_date = (Local::now() + Duration::days(1)).date_naive();
+
+        assert_eq!(parsed.date_naive(), expected_date);
+        assert_eq!(parsed.hour(), 9);
+        assert_eq!(parsed.minute(), 0);
+    }
+
+    #[test]
+    fn relative_datetime_still_accepts_bare_day_keywords() {
+        let now = Local::now();
+        let parsed = parse_relative_datetime("yesterday").unwrap();
+        assert!(parsed <= now && parsed > now - Duration::days(2));
+    }
+
+    #[test]
+    fn time_of_day_accepts_bare_hour_am_pm() {
+        use chrono::Timelike;
+
+        let nine_am = parse_time_of_day("9am").unwrap();
+        assert_eq!((nine_am.hour(), nine_am.minute()), (9, 0));
+
+        let twelve_pm = parse_time_of_day("12pm").unwrap();
+        assert_eq!((twelve_pm.hour(), twelve_pm.minute()), (12, 0));
+
+        let nine_pm = parse_time_of_day("9 pm").unwrap();
+        assert_eq!((nine_pm.hour(), nine_pm.minute()), (21, 0));
+    }
+
+    #[test]
+    fn time_of_day_accepts_colon_separated_times() {
+        use chrono::Timelike;
+
+        let time = parse_time_of_day("9:30am").unwrap();
+        assert_eq!((time.hour(), time.minute()), (9, 30));
+
+        let time = parse_time_of_day("21:00").unwrap();
+        assert_eq!((time.hour(), time.minute()), (21, 0));
+    }
+
+    #[test]
+    fn adjacent_filter_clauses_without_and_compose_instead_of_clobbering() {
+        let settings = Settings::default();
+        let mut task = stub_task();
+        task.status = TaskStatus::Running;
+        task.label = Some("foo".to_string());
+
+        // Two separate `filter_query` clauses (no `and` between them) should both
+        // apply, not have the second silently overwrite the first.
+        let result = apply_query(
+            &["status=running label=foo".to_string()],
+            &settings,
+        )
+        .unwrap();
+        assert_eq!(result.filters.len(), 2);
+        assert!(result.filters.iter().all(|filter| filter(&task)));
+
+        let mut non_matching = stub_task();
+        non_matching.status = TaskStatus::Queued;
+        non_matching.label = Some("foo".to_string());
+        assert!(!result.filters.iter().all(|filter| filter(&non_matching)));
+    }
+}
@@ -15,7 +15,12 @@ fn construct_success_clean_message(message: CleanRequest) -> String {
         .map(|name| format!(" from group '{name}'"))
         .unwrap_or_default();
 
-    format!("All{successful_only_fix} finished tasks have been removed{group_fix}")
+    let before_fix = message
+        .before
+        .map(|before| format!(" that finished before {before}"))
+        .unwrap_or_default();
+
+    format!("All{successful_only_fix} finished tasks have been removed{group_fix}{before_fix}")
 }
 
 /// Invoked when calling `pueue clean`.
@@ -54,6 +59,15 @@ pub fn clean(settings: &Settings, state: &SharedState, message: CleanRequest) ->
                 }
             }
         }
+
+        // Users can limit the clean-up to tasks that finished before a cutoff.
+        // Tasks without a recorded `end` are never removed by this filter.
+        if let Some(before) = message.before {
+            match state.tasks().get(task_id).and_then(|task| task.end) {
+                Some(end) if end < before => {}
+                _ => continue,
+            }
+        }
         let _ = state.tasks_mut().remove(task_id).unwrap();
         clean_log_handles(*task_id, &settings.shared.pueue_directory());
     }
@@ -65,16 +79,22 @@ pub fn clean(settings: &Settings, state: &SharedState, message: CleanRequest) ->
 
 #[cfg(test)]
 mod tests {
+    use chrono::{DateTime, Duration, Local};
     use pretty_assertions::assert_eq;
     use tempfile::TempDir;
 
     use super::{super::fixtures::*, *};
     use crate::daemon::internal_state::state::InternalState;
 
-    fn get_message(successful_only: bool, group: Option<String>) -> CleanRequest {
+    fn get_message(
+        successful_only: bool,
+        group: Option<String>,
+        before: Option<DateTime<Local>>,
+    ) -> CleanRequest {
         CleanRequest {
             successful_only,
             group,
+            before,
         }
     }
 
@@ -118,7 +138,7 @@ mod tests {
         let (state, settings, _tempdir) = get_stub_state();
 
         // Only task 1 will be removed, since it's the only TaskStatus with `Done`.
-        let message = clean(&settings, &state, get_message(false, None));
+        let message = clean(&settings, &state, get_message(false, None, None));
 
         // Return message is correct
         assert!(matches!(message, Response::Success(_)));
@@ -135,7 +155,7 @@ mod tests {
         let (state, settings, _tempdir) = get_clean_test_state(&[PUEUE_DEFAULT_GROUP]);
 
         // All finished tasks should removed when calling default `clean`.
-        let message = clean(&settings, &state, get_message(false, None));
+        let message = clean(&settings, &state, get_message(false, None, None));
 
         // Return message is correct
         assert!(matches!(message, Response::Success(_)));
@@ -153,7 +173,7 @@ mod tests {
 
         // Only successfully finished tasks should get removed when
         // calling `clean` with the `successful_only` flag.
-        let message = clean(&settings, &state, get_message(true, None));
+        let message = clean(&settings, &state, get_message(true, None, None));
 
         // Return message is correct
         assert!(matches!(message, Response::Success(_)));
@@ -172,7 +192,7 @@ mod tests {
         let (state, settings, _tempdir) = get_clean_test_state(&[PUEUE_DEFAULT_GROUP, "other"]);
 
         // All finished tasks should removed in selected group (other)
-        let message = clean(&settings, &state, get_message(false, Some("other".into())));
+        let message = clean(&settings, &state, get_message(false, Some("other".into()), None));
 
         // Return message is correct
         assert!(matches!(message, Response::Success(_)));
@@ -195,7 +215,7 @@ mod tests {
         let (state, settings, _tempdir) = get_clean_test_state(&[PUEUE_DEFAULT_GROUP, "other"]);
 
         // Only successfully finished tasks should removed in the 'other' group
-        let message = clean(&settings, &state, get_message(true, Some("other".into())));
+        let message = clean(&settings, &state, get_message(true, Some("other".into()), None));
 
         // Return message is correct
         assert!(matches!(message, Response::Success(_)));
@@ -213,4 +233,35 @@ mod tests {
         assert_eq!(state.tasks().len(), 11);
         assert!(!state.tasks().contains_key(&6));
     }
+
+    #[test]
+    fn clean_before_cutoff() {
+        let (state, settings, _tempdir) = get_clean_test_state(&[PUEUE_DEFAULT_GROUP]);
+
+        let now = Local::now();
+        {
+            let mut state = state.lock().unwrap();
+            // Tasks 0 and 1 finished well over a week ago, the rest finished just now.
+            state.tasks_mut().get_mut(&0).unwrap().end = Some(now - Duration::days(10));
+            state.tasks_mut().get_mut(&1).unwrap().end = Some(now - Duration::days(8));
+            for id in 2..=5 {
+                state.tasks_mut().get_mut(&id).unwrap().end = Some(now);
+            }
+        }
+
+        // Only tasks that finished before the cutoff should be removed.
+        let message = clean(
+            &settings,
+            &state,
+            get_message(false, None, Some(now - Duration::weeks(1))),
+        );
+
+        assert!(matches!(message, Response::Success(_)));
+
+        // Assert that only the two old tasks have been removed.
+        let state = state.lock().unwrap();
+        assert_eq!(state.tasks().len(), 4);
+        assert!(!state.tasks().contains_key(&0));
+        assert!(!state.tasks().contains_key(&1));
+    }
 }
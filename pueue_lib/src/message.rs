@@ -0,0 +1,16 @@
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+
+/// Sent from the client to the daemon to request the removal of finished tasks.
+///
+/// Constructed from `SubCommand::Clean` on the client side.
+#[derive(PartialEq, Eq, Clone, Debug, Serialize, Deserialize)]
+pub struct CleanRequest {
+    /// Only clean tasks that finished successfully.
+    pub successful_only: bool,
+    /// Only clean tasks of a specific group.
+    pub group: Option<String>,
+    /// Only clean tasks whose `end` lies before this cutoff. Tasks without a
+    /// recorded `end` are never removed by this filter.
+    pub before: Option<DateTime<Local>>,
+}